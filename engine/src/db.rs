@@ -1,5 +1,9 @@
-use crate::models::FileEntry;
-use rusqlite::{params, Connection, Result};
+use crate::models::{ChunkOverlap, ChunkRecord, DuplicateFile, DuplicateGroup, FileEntry};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+/// Minimum fraction of the smaller file's bytes that must be shared for a chunk
+/// overlap to be worth reporting in `Database::shared_chunk_report`.
+const SIGNIFICANT_OVERLAP_RATIO: f64 = 0.1;
 
 pub struct Database {
     conn: Connection,
@@ -30,22 +34,70 @@ impl Database {
                 size_bytes INTEGER NOT NULL,
                 created_at INTEGER,
                 modified_at INTEGER,
-                md5_hash TEXT NOT NULL,
+                md5_hash TEXT,
                 sha256_hash TEXT,
                 sha256_verified INTEGER DEFAULT 0
             );
 
-            -- Indexes for Search Performance
+            -- Content-defined chunks, used to find partial/near-duplicate files that
+            -- whole-file hashing can't see.
+            CREATE TABLE IF NOT EXISTS chunks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                file_id INTEGER NOT NULL REFERENCES files(id),
+                chunk_index INTEGER NOT NULL,
+                offset INTEGER NOT NULL,
+                length INTEGER NOT NULL,
+                chunk_hash TEXT NOT NULL
+            );
+            ",
+        )?;
+
+        // Columns added by later builds: `CREATE TABLE IF NOT EXISTS` above is a no-op
+        // against a `files` table created by an older build, so upgrade it in place
+        // rather than assuming the column is already there.
+        self.ensure_column("files", "partial_hash", "TEXT NOT NULL DEFAULT ''")?;
+        self.ensure_column("files", "mime_type", "TEXT")?;
+
+        self.conn.execute_batch(
+            "-- Indexes for Search Performance
             CREATE INDEX IF NOT EXISTS idx_path ON files(path);
             CREATE INDEX IF NOT EXISTS idx_filename ON files(filename);
             CREATE INDEX IF NOT EXISTS idx_extension ON files(extension);
             CREATE INDEX IF NOT EXISTS idx_size ON files(size_bytes);
             CREATE INDEX IF NOT EXISTS idx_md5 ON files(md5_hash);
-            
+            CREATE INDEX IF NOT EXISTS idx_mime_type ON files(mime_type);
+
             -- Composite Index for fast Duplicate Detection candidates
             CREATE INDEX IF NOT EXISTS idx_dupe_check ON files(size_bytes, md5_hash);
+
+            -- Composite Index for grouping by the cheap sampled fingerprint before a
+            -- full MD5 is even computed
+            CREATE INDEX IF NOT EXISTS idx_partial_check ON files(size_bytes, partial_hash);
+
+            CREATE INDEX IF NOT EXISTS idx_chunk_file ON chunks(file_id);
+            CREATE INDEX IF NOT EXISTS idx_chunk_hash ON chunks(chunk_hash);
             ",
         )?;
+
+        Ok(())
+    }
+
+    /// Adds `column` to `table` if an older build created the table without it.
+    /// `CREATE TABLE IF NOT EXISTS` only applies to brand-new databases, so schema
+    /// additions to an existing table have to go through here instead.
+    fn ensure_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({table})"))?;
+        let has_column = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|name| name.ok())
+            .any(|name| name == column);
+
+        if !has_column {
+            self.conn.execute_batch(&format!(
+                "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+            ))?;
+        }
+
         Ok(())
     }
 
@@ -54,9 +106,9 @@ impl Database {
 
         {
             let mut stmt = tx.prepare(
-                "INSERT OR REPLACE INTO files 
-                (path, filename, extension, size_bytes, created_at, modified_at, md5_hash, sha256_hash, sha256_verified)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 0)"
+                "INSERT OR REPLACE INTO files
+                (path, filename, extension, size_bytes, created_at, modified_at, md5_hash, partial_hash, sha256_hash, sha256_verified, mime_type)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, 0, ?10)"
             )?;
 
             for file in files {
@@ -68,7 +120,133 @@ impl Database {
                     file.created_at,
                     file.modified_at,
                     file.md5_hash,
-                    file.sha256_hash
+                    file.partial_hash,
+                    file.sha256_hash,
+                    file.mime_type
+                ])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Rows that share `(size_bytes, md5_hash)` with at least one other row, grouped
+    /// together via `idx_dupe_check`. These are MD5 collisions that are worth paying
+    /// for a cryptographically strong SHA-256 confirmation.
+    pub fn candidate_duplicate_groups(&self) -> Result<Vec<Vec<(i64, String)>>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, path, size_bytes, md5_hash FROM files
+             WHERE md5_hash IS NOT NULL AND (size_bytes, md5_hash) IN (
+                 SELECT size_bytes, md5_hash FROM files
+                 WHERE md5_hash IS NOT NULL
+                 GROUP BY size_bytes, md5_hash
+                 HAVING COUNT(*) > 1
+             )
+             ORDER BY size_bytes, md5_hash",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, u64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+            ))
+        })?;
+
+        let mut groups: Vec<Vec<(i64, String)>> = Vec::new();
+        let mut current_key: Option<(u64, String)> = None;
+
+        for row in rows {
+            let (size, md5, id, path) = row?;
+            let key = (size, md5);
+            if current_key.as_ref() != Some(&key) {
+                groups.push(Vec::new());
+                current_key = Some(key);
+            }
+            groups.last_mut().unwrap().push((id, path));
+        }
+
+        Ok(groups)
+    }
+
+    /// Looks up the last indexed `(size_bytes, modified_at)` for a path, so a re-scan
+    /// can tell whether a file actually changed since it was last hashed.
+    pub fn lookup_meta(&self, path: &str) -> Result<Option<(u64, i64)>> {
+        self.conn
+            .query_row(
+                "SELECT size_bytes, modified_at FROM files WHERE path = ?1",
+                params![path],
+                |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)?)),
+            )
+            .optional()
+    }
+
+    /// Existing rows (typically from an earlier scan) sharing a given `(size_bytes,
+    /// partial_hash)`, with whichever MD5 they already have (if any). Lets a re-scan
+    /// fold previously-indexed files into this run's duplicate-candidate grouping,
+    /// since an `Unchanged` file is never reconsidered on its own.
+    pub fn lookup_partial_hash_group(
+        &self,
+        size_bytes: u64,
+        partial_hash: &str,
+    ) -> Result<Vec<(i64, String, Option<String>)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, path, md5_hash FROM files WHERE size_bytes = ?1 AND partial_hash = ?2",
+        )?;
+        let rows = stmt.query_map(params![size_bytes, partial_hash], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.collect()
+    }
+
+    /// Backfills the full MD5 for a row that was left unhashed because it looked
+    /// unique at the time, now that a same-size, same-fingerprint sibling has shown up.
+    pub fn update_md5(&self, id: i64, md5_hash: &str) -> Result<()> {
+        self.conn
+            .execute("UPDATE files SET md5_hash = ?1 WHERE id = ?2", params![md5_hash, id])?;
+        Ok(())
+    }
+
+    /// Records the SHA-256 confirmation for a candidate row and marks it verified.
+    pub fn update_sha256(&self, id: i64, sha256_hash: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE files SET sha256_hash = ?1, sha256_verified = 1 WHERE id = ?2",
+            params![sha256_hash, id],
+        )?;
+        Ok(())
+    }
+
+    /// Rows that haven't been chunked yet, so a `chunk` run only touches files that are
+    /// new since the last one.
+    pub fn files_without_chunks(&self) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, path FROM files
+             WHERE id NOT IN (SELECT DISTINCT file_id FROM chunks)",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Replaces a file's chunk rows with a freshly computed set.
+    pub fn replace_chunks(&mut self, file_id: i64, chunks: &[ChunkRecord]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM chunks WHERE file_id = ?1", params![file_id])?;
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO chunks (file_id, chunk_index, offset, length, chunk_hash)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+
+            for chunk in chunks {
+                stmt.execute(params![
+                    file_id,
+                    chunk.chunk_index as i64,
+                    chunk.offset as i64,
+                    chunk.length as i64,
+                    chunk.chunk_hash
                 ])?;
             }
         }
@@ -76,4 +254,90 @@ impl Database {
         tx.commit()?;
         Ok(())
     }
+
+    /// Joins chunks across files on `chunk_hash` to surface pairs that share
+    /// significant regions — partial downloads, appended logs, re-encodes, etc. —
+    /// that whole-file hashing alone can't find. Pairs below `SIGNIFICANT_OVERLAP_RATIO`
+    /// are dropped, since two large files sharing one boilerplate chunk out of hundreds
+    /// isn't a meaningful overlap.
+    pub fn shared_chunk_report(&self) -> Result<Vec<ChunkOverlap>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT fa.path, fb.path, fa.size_bytes, fb.size_bytes, COUNT(*), SUM(ca.length)
+             FROM chunks ca
+             JOIN chunks cb ON ca.chunk_hash = cb.chunk_hash AND ca.file_id < cb.file_id
+             JOIN files fa ON fa.id = ca.file_id
+             JOIN files fb ON fb.id = cb.file_id
+             GROUP BY ca.file_id, cb.file_id
+             ORDER BY SUM(ca.length) DESC",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let file_a_size = row.get::<_, i64>(2)? as u64;
+            let file_b_size = row.get::<_, i64>(3)? as u64;
+            let shared_bytes = row.get::<_, i64>(5)? as u64;
+            let smaller = file_a_size.min(file_b_size).max(1);
+
+            Ok(ChunkOverlap {
+                file_a: row.get(0)?,
+                file_b: row.get(1)?,
+                file_a_size,
+                file_b_size,
+                shared_chunks: row.get::<_, i64>(4)? as usize,
+                shared_bytes,
+                overlap_ratio: shared_bytes as f64 / smaller as f64,
+            })
+        })?;
+
+        let mut overlaps = rows.collect::<Result<Vec<_>>>()?;
+        overlaps.retain(|o| o.overlap_ratio >= SIGNIFICANT_OVERLAP_RATIO);
+        Ok(overlaps)
+    }
+
+    /// Groups files confirmed identical by `md5_hash` — the stable identity for the
+    /// whole cluster, unlike `sha256_hash`, which only some members may have if a
+    /// `verify` run hasn't covered every row yet. Partitioning on whether a row happens
+    /// to be SHA-256-verified would split one real duplicate cluster into several
+    /// groups the moment `verify` runs on only part of it, so that flag is surfaced
+    /// per-row via `DuplicateFile::sha256_verified` instead of feeding the partition
+    /// key. Only groups with more than one member are returned.
+    pub fn duplicate_groups(&self) -> Result<Vec<DuplicateGroup>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, path, size_bytes, created_at, extension, md5_hash, sha256_verified
+             FROM files
+             WHERE md5_hash IS NOT NULL
+             ORDER BY md5_hash, created_at",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(5)?,
+                DuplicateFile {
+                    id: row.get(0)?,
+                    path: row.get(1)?,
+                    size_bytes: row.get::<_, i64>(2)? as u64,
+                    created_at: row.get(3)?,
+                    extension: row.get(4)?,
+                    sha256_verified: row.get::<_, i64>(6)? != 0,
+                },
+            ))
+        })?;
+
+        let mut groups: Vec<DuplicateGroup> = Vec::new();
+        let mut current_key: Option<String> = None;
+
+        for row in rows {
+            let (key, file) = row?;
+            if current_key.as_deref() != Some(key.as_str()) {
+                groups.push(DuplicateGroup {
+                    key: key.clone(),
+                    files: Vec::new(),
+                });
+                current_key = Some(key);
+            }
+            groups.last_mut().unwrap().files.push(file);
+        }
+
+        groups.retain(|g| g.files.len() > 1);
+        Ok(groups)
+    }
 }
@@ -6,6 +6,7 @@ use anyhow::Result;
 use db::Database;
 use scanner::Scanner;
 use serde::Serialize;
+use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::path::Path;
@@ -19,6 +20,14 @@ struct ScanProgress {
     total: Option<usize>, // Estimate, optional
     current_file: String,
     status: String, // "running", "completed"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    added: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unchanged: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<usize>,
 }
 
 fn write_status(path: &Path, progress: &ScanProgress) {
@@ -27,15 +36,40 @@ fn write_status(path: &Path, progress: &ScanProgress) {
     }
 }
 
+fn print_usage(program: &str) {
+    eprintln!("Usage: {} scan <scan_path> <db_path>", program);
+    eprintln!("       {} verify <db_path>", program);
+    eprintln!("       {} chunk <db_path>", program);
+    eprintln!("       {} overlaps <db_path>", program);
+    eprintln!("       {} dedup <db_path> [--apply]", program);
+}
+
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} <scan_path> <db_path>", args[0]);
+    if args.len() < 2 {
+        print_usage(&args[0]);
         std::process::exit(1);
     }
 
-    let scan_path = args[1].clone();
-    let db_path = args[2].clone();
+    match args[1].as_str() {
+        "scan" if args.len() >= 4 => run_scan(&args[2], &args[3]),
+        "verify" if args.len() >= 3 => run_verify(&args[2]),
+        "chunk" if args.len() >= 3 => run_chunk(&args[2]),
+        "overlaps" if args.len() >= 3 => run_overlaps(&args[2]),
+        "dedup" if args.len() >= 3 => {
+            let apply = args[3..].iter().any(|a| a == "--apply");
+            run_dedup(&args[2], apply)
+        }
+        _ => {
+            print_usage(&args[0]);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_scan(scan_path: &str, db_path: &str) -> Result<()> {
+    let scan_path = scan_path.to_string();
+    let db_path = db_path.to_string();
     let status_path = Path::new(&db_path)
         .parent()
         .unwrap()
@@ -51,6 +85,11 @@ fn main() -> Result<()> {
     let mut db = Database::new(&db_path)?;
     db.init()?;
 
+    // Separate connection so the scanner can look up existing metadata (and backfill
+    // MD5s of already-indexed duplicate candidates) while the writer thread below
+    // holds the connection used for inserts.
+    let read_db = Database::new(&db_path)?;
+
     // Create Channel
     let (tx, rx): (
         mpsc::Sender<crate::models::FileEntry>,
@@ -79,6 +118,10 @@ fn main() -> Result<()> {
                     total: None,
                     current_file: last_file.clone(),
                     status: "running".to_string(),
+                    added: None,
+                    updated: None,
+                    unchanged: None,
+                    skipped: None,
                 };
                 write_status(&status_path_clone, &progress);
             }
@@ -101,22 +144,280 @@ fn main() -> Result<()> {
     });
 
     let scanner = Scanner::new(&scan_path);
-    scanner.scan(tx);
+    let outcomes = scanner.scan(&read_db, tx);
 
     let total = db_handle.join().unwrap()?;
 
+    let added = outcomes
+        .iter()
+        .filter(|o| **o == scanner::ScanOutcome::Added)
+        .count();
+    let updated = outcomes
+        .iter()
+        .filter(|o| **o == scanner::ScanOutcome::Updated)
+        .count();
+    let unchanged = outcomes
+        .iter()
+        .filter(|o| **o == scanner::ScanOutcome::Unchanged)
+        .count();
+    let skipped = outcomes
+        .iter()
+        .filter(|o| **o == scanner::ScanOutcome::Skipped)
+        .count();
+
     // Write final status
     let final_progress = ScanProgress {
         scanned: total,
         total: Some(total),
         current_file: String::new(),
         status: "completed".to_string(),
+        added: Some(added),
+        updated: Some(updated),
+        unchanged: Some(unchanged),
+        skipped: Some(skipped),
     };
     write_status(&status_path, &final_progress);
 
     let duration = start_time.elapsed();
     println!("Scan complete in {:.2?}", duration);
-    println!("Total file indexed: {}", total);
+    println!(
+        "Added: {}, Updated: {}, Unchanged: {}, Skipped: {}",
+        added, updated, unchanged, skipped
+    );
+
+    Ok(())
+}
+
+/// Channel carrying `(file id, sha256 hash)` results from the verify pass to the DB
+/// writer thread. Aliased so the tuple-of-generics doesn't trip `clippy::type_complexity`.
+type Sha256Channel = (mpsc::Sender<(i64, String)>, mpsc::Receiver<(i64, String)>);
+
+fn run_verify(db_path: &str) -> Result<()> {
+    let start_time = Instant::now();
+
+    let db = Database::new(db_path)?;
+    db.init()?;
+
+    let groups = db.candidate_duplicate_groups()?;
+    let candidates: Vec<(i64, String)> = groups.into_iter().flatten().collect();
+    let total_candidates = candidates.len();
+
+    println!(
+        "Verifying {} MD5-collision candidates with SHA-256",
+        total_candidates
+    );
+
+    let (tx, rx): Sha256Channel = mpsc::channel();
+
+    let db_handle = thread::spawn(move || -> Result<usize> {
+        let mut verified = 0;
+        for (id, hash) in rx {
+            db.update_sha256(id, &hash)?;
+            verified += 1;
+        }
+        Ok(verified)
+    });
+
+    Scanner::verify(candidates, tx);
+
+    let verified = db_handle.join().unwrap()?;
+
+    let duration = start_time.elapsed();
+    println!("Verification complete in {:.2?}", duration);
+    println!("Confirmed {}/{} candidates", verified, total_candidates);
+
+    Ok(())
+}
+
+/// Channel carrying `(file id, chunks)` results from the chunking pass to the DB
+/// writer thread. Aliased so the tuple-of-generics doesn't trip `clippy::type_complexity`.
+type ChunkChannel = (
+    mpsc::Sender<(i64, Vec<crate::models::ChunkRecord>)>,
+    mpsc::Receiver<(i64, Vec<crate::models::ChunkRecord>)>,
+);
+
+fn run_chunk(db_path: &str) -> Result<()> {
+    let start_time = Instant::now();
+
+    let mut db = Database::new(db_path)?;
+    db.init()?;
+
+    let candidates = db.files_without_chunks()?;
+    let total_candidates = candidates.len();
+
+    println!(
+        "Cutting content-defined chunks for {} files",
+        total_candidates
+    );
+
+    let (tx, rx): ChunkChannel = mpsc::channel();
+
+    let db_handle = thread::spawn(move || -> Result<usize> {
+        let mut chunked = 0;
+        for (file_id, chunks) in rx {
+            db.replace_chunks(file_id, &chunks)?;
+            chunked += 1;
+        }
+        Ok(chunked)
+    });
+
+    Scanner::chunk_files(candidates, tx);
+
+    let chunked = db_handle.join().unwrap()?;
+
+    let duration = start_time.elapsed();
+    println!("Chunking complete in {:.2?}", duration);
+    println!("Chunked {}/{} files", chunked, total_candidates);
+
+    Ok(())
+}
+
+/// Prints the significant chunk overlaps found by `chunk`, i.e. files that share
+/// enough content to be partial/near-duplicates. Byte-identical copies are listed
+/// too; pairs are not filtered on whether their full hashes differ.
+fn run_overlaps(db_path: &str) -> Result<()> {
+    let db = Database::new(db_path)?;
+    db.init()?;
+
+    let overlaps = db.shared_chunk_report()?;
+
+    if overlaps.is_empty() {
+        println!("No significant chunk overlaps found.");
+        return Ok(());
+    }
+
+    println!("Significant chunk overlaps ({} pairs):", overlaps.len());
+    for overlap in &overlaps {
+        println!(
+            "  {:.0}% overlap ({} bytes across {} chunks): {} ({} bytes) <-> {} ({} bytes)",
+            overlap.overlap_ratio * 100.0,
+            overlap.shared_bytes,
+            overlap.shared_chunks,
+            overlap.file_a,
+            overlap.file_a_size,
+            overlap.file_b,
+            overlap.file_b_size,
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct DedupManifestEntry {
+    keep: String,
+    keep_id: i64,
+    redundant: String,
+    redundant_id: i64,
+    size_bytes: u64,
+}
+
+/// Prints each duplicate group with a chosen keeper, writes a JSON manifest of
+/// reclaimable bytes, and (only with `apply`) replaces the redundant copies with
+/// hardlinks to the keeper. Defaults to a dry run so nothing is touched on disk
+/// unless explicitly requested.
+fn run_dedup(db_path: &str, apply: bool) -> Result<()> {
+    let db = Database::new(db_path)?;
+    db.init()?;
+
+    let groups = db.duplicate_groups()?;
+
+    let mut manifest = Vec::new();
+    let mut wasted_by_extension: HashMap<String, u64> = HashMap::new();
+    let mut total_wasted: u64 = 0;
+
+    for group in &groups {
+        // Keeper: the oldest copy, tie-broken by the shortest path.
+        let mut files = group.files.clone();
+        files.sort_by(|a, b| {
+            a.created_at
+                .cmp(&b.created_at)
+                .then_with(|| a.path.len().cmp(&b.path.len()))
+        });
+        let (keeper, redundant) = files.split_first().unwrap();
+
+        println!(
+            "Group {} ({} bytes, {} copies):",
+            group.key,
+            keeper.size_bytes,
+            files.len()
+        );
+        println!("  keep:      {} (id {})", keeper.path, keeper.id);
+
+        for dup in redundant {
+            println!("  redundant: {} (id {})", dup.path, dup.id);
+
+            let extension = dup
+                .extension
+                .clone()
+                .unwrap_or_else(|| "(none)".to_string());
+            *wasted_by_extension.entry(extension).or_insert(0) += dup.size_bytes;
+            total_wasted += dup.size_bytes;
+
+            manifest.push(DedupManifestEntry {
+                keep: keeper.path.clone(),
+                keep_id: keeper.id,
+                redundant: dup.path.clone(),
+                redundant_id: dup.id,
+                size_bytes: dup.size_bytes,
+            });
+
+            if apply {
+                match replace_with_hardlink(&keeper.path, &dup.path) {
+                    Ok(()) => println!("             -> hardlinked to keeper"),
+                    Err(e) => eprintln!("             ! failed to hardlink: {}", e),
+                }
+            }
+        }
+    }
+
+    println!("\nWasted space by extension:");
+    let mut by_extension: Vec<(String, u64)> = wasted_by_extension.into_iter().collect();
+    by_extension.sort_by_key(|b| std::cmp::Reverse(b.1));
+    for (extension, bytes) in &by_extension {
+        println!("  {:<12} {} bytes", extension, bytes);
+    }
+    println!(
+        "Total reclaimable: {} bytes across {} redundant files in {} groups",
+        total_wasted,
+        manifest.len(),
+        groups.len()
+    );
+
+    let manifest_path = Path::new(db_path)
+        .parent()
+        .unwrap()
+        .join("dedup_manifest.json");
+    if let Ok(file) = File::create(&manifest_path) {
+        let _ = serde_json::to_writer_pretty(file, &manifest);
+    }
+    println!("Manifest written to {:?}", manifest_path);
+
+    if apply {
+        println!("Applied: redundant copies were replaced with hardlinks to their keeper.");
+    } else {
+        println!("Dry run: no files were modified. Re-run with --apply to hardlink redundant copies.");
+    }
+
+    Ok(())
+}
+
+/// Replaces `dup_path` with a hardlink to `keeper_path`, so both names point at the
+/// same inode and no bytes are actually deleted until every link is removed.
+///
+/// Links into a temp path next to `dup_path` first and only `rename`s it over the
+/// original once the link has actually succeeded, so a failed link (cross-device,
+/// permissions, keeper moved away, disk full) never leaves `dup_path` deleted with
+/// nothing in its place.
+fn replace_with_hardlink(keeper_path: &str, dup_path: &str) -> std::io::Result<()> {
+    let tmp_path = format!("{dup_path}.dedup-tmp");
+
+    std::fs::hard_link(keeper_path, &tmp_path)?;
+
+    if let Err(e) = std::fs::rename(&tmp_path, dup_path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
 
     Ok(())
 }
@@ -9,12 +9,29 @@ pub struct FileEntry {
     pub size_bytes: u64,
     pub created_at: i64,  // Unix timestamp
     pub modified_at: i64, // Unix timestamp
-    pub md5_hash: String,
+    // Full MD5 is only computed for files that share a (size, partial_hash) group with
+    // another file; unique-looking files are left without one to avoid hashing their
+    // entire contents for nothing.
+    pub md5_hash: Option<String>,
+    // Cheap fingerprint used to bucket candidates before paying for a full hash. Small
+    // files (<= SAMPLED_CHECKSUM_THRESHOLD) just reuse their full MD5 here.
+    pub partial_hash: String,
     pub sha256_hash: Option<String>,
+    // Real type sniffed from the file's header bytes, independent of its extension.
+    pub mime_type: Option<String>,
 }
 
 impl FileEntry {
-    pub fn new(path: PathBuf, size: u64, created: i64, modified: i64, md5: String) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        path: PathBuf,
+        size: u64,
+        created: i64,
+        modified: i64,
+        md5: Option<String>,
+        partial_hash: String,
+        mime_type: Option<String>,
+    ) -> Self {
         let filename = path
             .file_name()
             .unwrap_or_default()
@@ -30,7 +47,56 @@ impl FileEntry {
             created_at: created,
             modified_at: modified,
             md5_hash: md5,
+            partial_hash,
             sha256_hash: None,
+            mime_type,
         }
     }
 }
+
+/// One content-defined chunk of a file, as produced by the rolling-hash cutter in
+/// `scanner.rs` and stored in the `chunks` table.
+#[derive(Debug, Clone)]
+pub struct ChunkRecord {
+    pub chunk_index: usize,
+    pub offset: u64,
+    pub length: u64,
+    pub chunk_hash: String,
+}
+
+/// A pair of files that share one or more chunks, as surfaced by
+/// `Database::shared_chunk_report`. `overlap_ratio` is `shared_bytes` over the smaller
+/// file's size, so a large file that happens to share one small chunk with everything
+/// doesn't drown out pairs that are mostly the same content.
+#[derive(Debug, Serialize, Clone)]
+pub struct ChunkOverlap {
+    pub file_a: String,
+    pub file_b: String,
+    pub file_a_size: u64,
+    pub file_b_size: u64,
+    pub shared_chunks: usize,
+    pub shared_bytes: u64,
+    pub overlap_ratio: f64,
+}
+
+/// A single row participating in a duplicate group, as returned by
+/// `Database::duplicate_groups`.
+#[derive(Debug, Clone)]
+pub struct DuplicateFile {
+    pub id: i64,
+    pub path: String,
+    pub size_bytes: u64,
+    pub created_at: i64,
+    pub extension: Option<String>,
+    // Whether this row has its own SHA-256 confirmation from a `verify` run. Purely
+    // informational — the group itself is always keyed by `md5_hash`, the one identity
+    // shared by every member regardless of which rows happen to be verified.
+    pub sha256_verified: bool,
+}
+
+/// A set of files confirmed identical by MD5 (the stable identity for the cluster).
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub key: String,
+    pub files: Vec<DuplicateFile>,
+}
@@ -1,17 +1,65 @@
-use crate::models::FileEntry;
+use crate::db::Database;
+use crate::models::{ChunkRecord, FileEntry};
 use anyhow::Result;
 use md5::{Digest, Md5};
 use rayon::prelude::*;
+use sha2::Sha256;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::Path;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
 use walkdir::WalkDir;
 
+/// Files at or below this size are cheap enough to hash in full, so the "partial"
+/// checksum is just their full MD5.
+const SAMPLED_CHECKSUM_THRESHOLD: u64 = 1024 * 1024; // 1 MiB
+/// Size of each sampled window.
+const SAMPLE_BLOCK_SIZE: usize = 16 * 1024; // 16 KiB
+/// Number of windows sampled from a large file (first, last, and evenly spaced interior blocks).
+const SAMPLE_BLOCK_COUNT: u64 = 4;
+/// How much of the file header to read when sniffing its MIME type from magic bytes.
+const MIME_SNIFF_LEN: usize = 8192;
+
+/// Width of the rolling-hash window used to cut content-defined chunks.
+const CDC_WINDOW: usize = 64;
+/// Low bits checked against zero to decide a chunk boundary; 13 bits gives ~8 KiB
+/// average chunk size.
+const CDC_MASK: u64 = (1 << 13) - 1;
+/// Lower bound on chunk size, so the mask check only starts once a chunk has grown
+/// past the rolling-hash window.
+const CDC_MIN_CHUNK: u64 = 2 * 1024;
+/// Upper bound on chunk size, to avoid pathologically large chunks when the rolling
+/// hash happens not to hit the mask for a long stretch.
+const CDC_MAX_CHUNK: u64 = 64 * 1024;
+/// Base used by the polynomial rolling hash.
+const CDC_ROLLING_BASE: u64 = 257;
+
 pub struct Scanner {
     root: String,
 }
 
+/// Metadata plus cheap fingerprint gathered during the first pass, before we know
+/// whether a file is worth fully hashing.
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    created: i64,
+    modified: i64,
+    partial_hash: String,
+    mime_type: Option<String>,
+}
+
+/// What happened to a given path during a re-scan, so `main.rs` can report a
+/// added/updated/unchanged/skipped summary instead of re-hashing everything blindly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanOutcome {
+    Added,
+    Updated,
+    Unchanged,
+    Skipped,
+}
+
 impl Scanner {
     pub fn new(root: &str) -> Self {
         Self {
@@ -19,46 +67,191 @@ impl Scanner {
         }
     }
 
-    pub fn scan(&self, tx: Sender<FileEntry>) {
-        WalkDir::new(&self.root)
+    /// Walks the tree, consulting `db` for each path's last-known `(size, modified_at)`
+    /// so unchanged files are skipped entirely, then fingerprints and (selectively)
+    /// hashes only the files that are new or have actually changed.
+    pub fn scan(&self, db: &Database, tx: Sender<FileEntry>) -> Vec<ScanOutcome> {
+        let mut outcomes = Vec::new();
+        let mut pending: Vec<(PathBuf, u64, i64, i64)> = Vec::new();
+
+        // Classification pass: sequential, since it consults the DB per path.
+        for entry in WalkDir::new(&self.root)
             .into_iter()
-            .par_bridge() // Parallelize the iterator
             .filter_map(|e| e.ok())
             .filter(|e| e.file_type().is_file())
-            .for_each_with(tx, |tx, entry| {
-                let path = entry.path();
-
-                // Skip if we can't read metadata
-                let metadata = match path.metadata() {
-                    Ok(m) => m,
-                    Err(_) => return,
-                };
-
-                let size = metadata.len();
-                // Basic Unix timestamps (or 0 if unavailable)
-                let created = metadata
-                    .created()
-                    .unwrap_or(std::time::SystemTime::UNIX_EPOCH)
-                    .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs() as i64;
-
-                let modified = metadata
-                    .modified()
-                    .unwrap_or(std::time::SystemTime::UNIX_EPOCH)
-                    .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs() as i64;
-
-                // Calculate MD5
-                if let Ok(hash) = compute_md5(path) {
-                    let entry = FileEntry::new(path.to_path_buf(), size, created, modified, hash);
-
-                    // Send to DB thread (ignore errors if receiver dropped)
-                    let _ = tx.send(entry);
+        {
+            let path = entry.path();
+
+            let metadata = match path.metadata() {
+                Ok(m) => m,
+                Err(_) => {
+                    outcomes.push(ScanOutcome::Skipped);
+                    continue;
+                }
+            };
+
+            let size = metadata.len();
+            let created = to_unix_secs(metadata.created());
+            let modified = to_unix_secs(metadata.modified());
+
+            let stored = db.lookup_meta(&path.to_string_lossy());
+            match stored {
+                Ok(Some((stored_size, stored_modified)))
+                    if stored_size == size && stored_modified == modified =>
+                {
+                    outcomes.push(ScanOutcome::Unchanged);
+                }
+                Ok(Some(_)) => {
+                    outcomes.push(ScanOutcome::Updated);
+                    pending.push((path.to_path_buf(), size, created, modified));
+                }
+                Ok(None) => {
+                    outcomes.push(ScanOutcome::Added);
+                    pending.push((path.to_path_buf(), size, created, modified));
+                }
+                Err(_) => {
+                    outcomes.push(ScanOutcome::Skipped);
+                }
+            }
+        }
+
+        // Fingerprinting pass: only the files that are new or changed, in parallel.
+        let candidates: Vec<Candidate> = pending
+            .into_par_iter()
+            .filter_map(|(path, size, created, modified)| {
+                let (partial_hash, mime_type) = fingerprint(&path, size).ok()?;
+                Some(Candidate {
+                    path,
+                    size,
+                    created,
+                    modified,
+                    partial_hash,
+                    mime_type,
+                })
+            })
+            .collect();
+
+        // Group by (size, partial_hash) so we only pay for a full MD5 when a file
+        // actually has a same-size, same-fingerprint sibling.
+        let mut group_counts: HashMap<(u64, String), usize> = HashMap::new();
+        for c in &candidates {
+            *group_counts
+                .entry((c.size, c.partial_hash.clone()))
+                .or_insert(0) += 1;
+        }
+
+        // Fold in already-indexed files sharing the same fingerprint: an `Unchanged`
+        // file from a prior scan is never reconsidered on its own, so without this a
+        // new file would go undetected as a duplicate of something already filed away
+        // as "unique". Any such sibling that was left unhashed for that reason gets
+        // its full MD5 backfilled now that the group is no longer a singleton.
+        //
+        // A candidate's own pre-scan row is excluded from `existing`: an `Updated` file
+        // whose mtime changed but whose content (and so `partial_hash`) didn't (a bare
+        // `touch`, a restore) would otherwise show up as its own "sibling", inflating
+        // its group for no reason and, since `insert_files` is about to `REPLACE` that
+        // row anyway, needlessly backfilling a value about to be overwritten.
+        let candidate_paths: std::collections::HashSet<String> = candidates
+            .iter()
+            .map(|c| c.path.to_string_lossy().to_string())
+            .collect();
+
+        let mut backfill: Vec<(i64, String)> = Vec::new();
+        for key in group_counts.keys().cloned().collect::<Vec<_>>() {
+            let (size, partial_hash) = &key;
+            if let Ok(existing) = db.lookup_partial_hash_group(*size, partial_hash) {
+                let existing: Vec<_> = existing
+                    .into_iter()
+                    .filter(|(_, path, _)| !candidate_paths.contains(path))
+                    .collect();
+
+                if existing.is_empty() {
+                    continue;
+                }
+
+                *group_counts.get_mut(&key).unwrap() += existing.len();
+
+                if group_counts[&key] > 1 {
+                    backfill.extend(
+                        existing
+                            .into_iter()
+                            .filter(|(_, _, md5)| md5.is_none())
+                            .map(|(id, path, _)| (id, path)),
+                    );
+                }
+            }
+        }
+
+        for (id, path) in backfill {
+            if let Ok(hash) = compute_md5(Path::new(&path)) {
+                let _ = db.update_md5(id, &hash);
+            }
+        }
+
+        // Hashing pass: resolve the full MD5 only for members of multi-file groups.
+        // For files at or under the sampled-checksum threshold, `partial_hash` already
+        // *is* the full MD5 (see `fingerprint`), so reuse it instead of re-reading and
+        // re-hashing the whole file for the same value.
+        candidates.into_par_iter().for_each_with(tx, |tx, c| {
+            let needs_full_hash = group_counts[&(c.size, c.partial_hash.clone())] > 1;
+            let md5_hash = if !needs_full_hash {
+                None
+            } else if c.size <= SAMPLED_CHECKSUM_THRESHOLD {
+                Some(c.partial_hash.clone())
+            } else {
+                compute_md5(&c.path).ok()
+            };
+
+            let entry = FileEntry::new(
+                c.path,
+                c.size,
+                c.created,
+                c.modified,
+                md5_hash,
+                c.partial_hash,
+                c.mime_type,
+            );
+
+            // Send to DB thread (ignore errors if receiver dropped)
+            let _ = tx.send(entry);
+        });
+
+        outcomes
+    }
+
+    /// Re-hashes each `(id, path)` candidate with a full SHA-256 and sends the result
+    /// to the DB writer thread. Meant to be run over the MD5 collision clusters
+    /// returned by `Database::candidate_duplicate_groups`, not the whole tree.
+    pub fn verify(candidates: Vec<(i64, String)>, tx: Sender<(i64, String)>) {
+        candidates
+            .into_par_iter()
+            .for_each_with(tx, |tx, (id, path)| {
+                if let Ok(hash) = compute_sha256(Path::new(&path)) {
+                    let _ = tx.send((id, hash));
                 }
             });
     }
+
+    /// Cuts each `(id, path)` candidate into content-defined chunks and sends the
+    /// resulting set to the DB writer thread. Meant to be run over files returned by
+    /// `Database::files_without_chunks`, not the whole tree.
+    pub fn chunk_files(candidates: Vec<(i64, String)>, tx: Sender<(i64, Vec<ChunkRecord>)>) {
+        candidates
+            .into_par_iter()
+            .for_each_with(tx, |tx, (id, path)| {
+                if let Ok(chunks) = cut_content_defined_chunks(Path::new(&path)) {
+                    let _ = tx.send((id, chunks));
+                }
+            });
+    }
+}
+
+// Basic Unix timestamp (or 0 if unavailable)
+fn to_unix_secs(time: std::io::Result<std::time::SystemTime>) -> i64 {
+    time.unwrap_or(std::time::SystemTime::UNIX_EPOCH)
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
 }
 
 fn compute_md5(path: &Path) -> Result<String> {
@@ -79,3 +272,220 @@ fn compute_md5(path: &Path) -> Result<String> {
     let result = hasher.finalize();
     Ok(format!("{:x}", result))
 }
+
+fn compute_sha256(path: &Path) -> Result<String> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+    let mut hasher = Sha256::new();
+
+    // Read in chunks to avoid loading large files entirely into RAM
+    let mut buffer = [0; 8192];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+
+    let result = hasher.finalize();
+    Ok(format!("{:x}", result))
+}
+
+/// Computes a file's cheap fingerprint and sniffs its MIME type in a single pass,
+/// opening the file once and reusing the header bytes read for sniffing as the start
+/// of the fingerprint hash instead of reading them twice.
+fn fingerprint(path: &Path, size: u64) -> Result<(String, Option<String>)> {
+    let mut file = File::open(path)?;
+
+    let mut header = [0u8; MIME_SNIFF_LEN];
+    let header_len = read_prefix(&mut file, &mut header)?;
+    let mime_type = sniff_mime(&header[..header_len]);
+
+    let partial_hash = if size <= SAMPLED_CHECKSUM_THRESHOLD {
+        let mut hasher = Md5::new();
+        hasher.update(&header[..header_len]);
+
+        let mut buffer = [0u8; 8192];
+        loop {
+            let count = file.read(&mut buffer)?;
+            if count == 0 {
+                break;
+            }
+            hasher.update(&buffer[..count]);
+        }
+        format!("{:x}", hasher.finalize())
+    } else {
+        compute_sampled_checksum(&mut file, size, &header[..header_len])?
+    };
+
+    Ok((partial_hash, mime_type))
+}
+
+/// Reads up to `buf.len()` bytes from the start of an already-open file, returning how
+/// many were actually available (fewer than `buf.len()` only for small files).
+fn read_prefix(file: &mut File, buf: &mut [u8]) -> Result<usize> {
+    let mut total_read = 0;
+    while total_read < buf.len() {
+        let count = file.read(&mut buf[total_read..])?;
+        if count == 0 {
+            break;
+        }
+        total_read += count;
+    }
+    Ok(total_read)
+}
+
+/// Magic-byte signatures for the file types this catalog cares most about.
+fn sniff_mime(header: &[u8]) -> Option<String> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xFF\xD8\xFF", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1F\x8B", "application/gzip"),
+        (b"7z\xBC\xAF\x27\x1C", "application/x-7z-compressed"),
+        (b"Rar!\x1A\x07", "application/vnd.rar"),
+        (b"ID3", "audio/mpeg"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"RIFF", "audio/x-wav"),
+        (b"\x7FELF", "application/x-elf"),
+        (b"MZ", "application/x-msdownload"),
+    ];
+
+    if header.len() >= 8 && &header[4..8] == b"ftyp" {
+        return Some("video/mp4".to_string());
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| header.starts_with(signature))
+        .map(|(_, mime)| mime.to_string())
+}
+
+/// Splits a file into content-defined chunks by sliding a polynomial rolling hash over
+/// a 64-byte window and cutting whenever its low bits are all zero, bounded to
+/// `[CDC_MIN_CHUNK, CDC_MAX_CHUNK]`. Unlike fixed-size chunking, boundaries are driven
+/// by content, so an insertion/deletion shifts only the chunks around it instead of
+/// every chunk after it - which is what lets `Database::shared_chunk_report` spot
+/// partial/near-duplicate files.
+fn cut_content_defined_chunks(path: &Path) -> Result<Vec<ChunkRecord>> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    // CDC_ROLLING_BASE^(CDC_WINDOW - 1), used to remove the oldest byte's contribution
+    // when the window slides.
+    let base_pow = (0..CDC_WINDOW - 1).fold(1u64, |acc, _| acc.wrapping_mul(CDC_ROLLING_BASE));
+
+    let mut window = [0u8; CDC_WINDOW];
+    let mut window_len = 0usize;
+    let mut window_pos = 0usize;
+    let mut rolling_hash: u64 = 0;
+
+    let mut chunks = Vec::new();
+    let mut chunk_hasher = Md5::new();
+    let mut chunk_index = 0usize;
+    let mut chunk_offset: u64 = 0;
+    let mut chunk_len: u64 = 0;
+    let mut offset: u64 = 0;
+
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+
+        for &byte in &buffer[..count] {
+            chunk_hasher.update([byte]);
+            chunk_len += 1;
+            offset += 1;
+
+            if window_len == CDC_WINDOW {
+                let outgoing = window[window_pos];
+                rolling_hash = rolling_hash.wrapping_sub((outgoing as u64).wrapping_mul(base_pow));
+            } else {
+                window_len += 1;
+            }
+            rolling_hash = rolling_hash
+                .wrapping_mul(CDC_ROLLING_BASE)
+                .wrapping_add(byte as u64);
+            window[window_pos] = byte;
+            window_pos = (window_pos + 1) % CDC_WINDOW;
+
+            let at_boundary = window_len == CDC_WINDOW
+                && chunk_len >= CDC_MIN_CHUNK
+                && rolling_hash & CDC_MASK == 0;
+
+            if at_boundary || chunk_len >= CDC_MAX_CHUNK {
+                chunks.push(ChunkRecord {
+                    chunk_index,
+                    offset: chunk_offset,
+                    length: chunk_len,
+                    chunk_hash: format!("{:x}", chunk_hasher.finalize_reset()),
+                });
+                chunk_index += 1;
+                chunk_offset = offset;
+                chunk_len = 0;
+                window_len = 0;
+                rolling_hash = 0;
+            }
+        }
+    }
+
+    if chunk_len > 0 {
+        chunks.push(ChunkRecord {
+            chunk_index,
+            offset: chunk_offset,
+            length: chunk_len,
+            chunk_hash: format!("{:x}", chunk_hasher.finalize_reset()),
+        });
+    }
+
+    Ok(chunks)
+}
+
+/// Cheap fingerprint for files over `SAMPLED_CHECKSUM_THRESHOLD`: hashes a handful of
+/// fixed-size windows (first, last, evenly spaced interior blocks) instead of reading
+/// the whole file. `header` is the first `MIME_SNIFF_LEN` bytes the caller already read
+/// while sniffing the MIME type, and `file` is positioned right after them, so the
+/// start of the first (offset-0) block doesn't get read from disk twice.
+fn compute_sampled_checksum(file: &mut File, size: u64, header: &[u8]) -> Result<String> {
+    let mut hasher = Md5::new();
+    let block_size = SAMPLE_BLOCK_SIZE as u64;
+
+    let mut offsets = vec![0u64];
+    for i in 1..SAMPLE_BLOCK_COUNT - 1 {
+        offsets.push(i * size / SAMPLE_BLOCK_COUNT);
+    }
+    offsets.push(size.saturating_sub(block_size));
+
+    let mut buffer = vec![0u8; SAMPLE_BLOCK_SIZE];
+    for offset in offsets {
+        let mut total_read = if offset == 0 {
+            // Already have the start of this block from the MIME-sniff read, and
+            // `file` is already positioned right after it.
+            buffer[..header.len()].copy_from_slice(header);
+            header.len()
+        } else {
+            file.seek(SeekFrom::Start(offset))?;
+            0
+        };
+
+        while total_read < buffer.len() {
+            let count = file.read(&mut buffer[total_read..])?;
+            if count == 0 {
+                break;
+            }
+            total_read += count;
+        }
+        hasher.update(&buffer[..total_read]);
+    }
+
+    hasher.update(size.to_le_bytes());
+    Ok(format!("{:x}", hasher.finalize()))
+}